@@ -0,0 +1,23 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+
+/// Persisted settings for `connect`, loaded from a YAML file so a rig doesn't need to be
+/// re-typed on the command line every time.
+#[derive(Debug, Default, Deserialize)]
+pub struct AppConfig {
+    pub port: Option<u16>,
+    pub lights: Option<Vec<String>>,
+    pub scan_timeout: Option<u64>,
+    /// Maps a custom OSC address (whatever a show-control app already emits) onto one of the
+    /// built-in channel addresses (`/red`, `/green`, `/blue`, `/warm`, `/cool`).
+    #[serde(default)]
+    pub addresses: HashMap<String, String>,
+}
+
+pub fn load_config(path: &str) -> AppConfig {
+    let contents = fs::read_to_string(path)
+        .unwrap_or_else(|err| panic!("Could not read config file {0}: {1}", path, err));
+    serde_yaml::from_str(&contents)
+        .unwrap_or_else(|err| panic!("Could not parse config file {0}: {1}", path, err))
+}
@@ -1,11 +1,11 @@
-use btleplug::api::{BDAddr, Central, Peripheral}; // UUID
+use btleplug::api::{BDAddr, Central, CentralEvent, Peripheral}; // UUID
 #[cfg(target_os = "linux")]
 use btleplug::bluez::{adapter::ConnectedAdapter, manager::Manager};
 #[cfg(target_os = "macos")]
 use btleplug::corebluetooth::{adapter::Adapter, manager::Manager};
 #[cfg(target_os = "windows")]
 use btleplug::winrtble::{adapter::Adapter, manager::Manager};
-use std::thread;
+use std::sync::mpsc::channel;
 use std::time::Duration;
 
 #[cfg(any(target_os = "windows", target_os = "macos"))]
@@ -24,9 +24,28 @@ fn get_central(manager: &Manager) -> ConnectedAdapter {
 pub struct DeviceInfo {
     pub name: Option<String>,
     pub address: BDAddr,
+    pub rssi: i16,
 }
 
-pub fn discover_devices(timeout: u64) -> Vec<DeviceInfo> {
+/// Narrows a scan down to devices worth showing the user.
+#[derive(Default, Clone)]
+pub struct ScanFilter {
+    /// Only keep devices whose advertised name starts with this prefix (case-insensitive).
+    pub name_prefix: Option<String>,
+}
+
+fn matches_filter(device: &DeviceInfo, filter: &ScanFilter) -> bool {
+    match &filter.name_prefix {
+        Some(prefix) => device
+            .name
+            .as_ref()
+            .map(|name| name.to_lowercase().starts_with(&prefix.to_lowercase()))
+            .unwrap_or(false),
+        None => true,
+    }
+}
+
+pub fn discover_devices(timeout: u64, filter: &ScanFilter) -> Vec<DeviceInfo> {
     let manager = Manager::new().unwrap();
 
     // get the first bluetooth adapter
@@ -34,21 +53,45 @@ pub fn discover_devices(timeout: u64) -> Vec<DeviceInfo> {
     // connect to the adapter
     let central = get_central(&manager);
 
-    // start scanning for devices
+    // subscribe to discovery events instead of sleeping and polling, so devices that
+    // advertise late are still picked up within `timeout`
+    let (event_send, event_recv) = channel();
+    central.on_event(Box::new(move |event| {
+        if let CentralEvent::DeviceDiscovered(address) = event {
+            let _ = event_send.send(address);
+        }
+    }));
+
     central.start_scan().unwrap();
-    // instead of waiting, you can use central.on_event to be notified of
-    // new devices
-    thread::sleep(Duration::from_secs(timeout));
+
+    let deadline = Duration::from_secs(timeout);
+    let mut seen: Vec<BDAddr> = Vec::new();
+    let start = std::time::Instant::now();
+    while start.elapsed() < deadline {
+        if let Ok(address) = event_recv.recv_timeout(deadline.saturating_sub(start.elapsed())) {
+            if !seen.contains(&address) {
+                seen.push(address);
+            }
+        }
+    }
 
     // find the device we're interested in
-    let devices: Vec<DeviceInfo> = central
+    let mut devices: Vec<DeviceInfo> = central
         .peripherals()
         .into_iter()
-        .map(|p| DeviceInfo {
-            name: p.properties().local_name,
-            address: p.properties().address,
+        .filter(|p| seen.contains(&p.properties().address))
+        .map(|p| {
+            let properties = p.properties();
+            DeviceInfo {
+                name: properties.local_name,
+                address: properties.address,
+                rssi: properties.rssi.unwrap_or(0) as i16,
+            }
         })
+        .filter(|d| matches_filter(d, filter))
         .collect();
 
-    return devices;
+    devices.sort_by(|a, b| b.rssi.cmp(&a.rssi));
+
+    devices
 }
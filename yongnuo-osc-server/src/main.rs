@@ -2,6 +2,7 @@
 // use std::net::UdpSocket;
 use clap::{App, Arg, ArgMatches, SubCommand};
 use std::process::exit;
+mod config;
 mod discover;
 mod server;
 
@@ -17,14 +18,18 @@ fn discover(matches: &ArgMatches) {
         .unwrap_or_default();
     println!("Discovering available lights... {0}s", timeout);
 
-    let devices: Vec<discover::DeviceInfo> = discover::discover_devices(timeout);
+    let filter = discover::ScanFilter {
+        name_prefix: matches.value_of("prefix").map(String::from),
+    };
+    let devices: Vec<discover::DeviceInfo> = discover::discover_devices(timeout, &filter);
     let devices: Vec<String> = devices
         .into_iter()
         .map(|d| {
             format!(
-                "{1} ({0})",
+                "{1} ({0}) [{2} dBm]",
                 d.name.unwrap_or("Unknown".to_string()),
-                d.address
+                d.address,
+                d.rssi
             )
         })
         .collect();
@@ -34,17 +39,30 @@ fn discover(matches: &ArgMatches) {
 }
 
 fn connect(matches: &ArgMatches) {
-    let mac_address = matches.value_of("mac").unwrap_or_default();
-    let port: u16 = matches
-        .value_of("port")
-        .unwrap_or_default()
-        .parse()
-        .ok()
+    let cli_macs: Vec<String> = matches
+        .values_of("mac")
+        .map(|values| values.map(String::from).collect())
         .unwrap_or_default();
+    let cli_port: Option<u16> = matches.value_of("port").and_then(|p| p.parse().ok());
+
+    let config = matches
+        .value_of("config")
+        .map(config::load_config)
+        .unwrap_or_default();
+
+    let macs = if !cli_macs.is_empty() {
+        cli_macs
+    } else {
+        config.lights.unwrap_or_default()
+    };
+    let port = cli_port
+        .or(config.port)
+        .unwrap_or(DEFAULT_PORT.parse().unwrap());
+    let scan_timeout = config.scan_timeout.unwrap_or(server::DEFAULT_SCAN_TIMEOUT);
 
     println!("OSC server on port {0}.", port);
 
-    server::serve(port, mac_address);
+    server::serve(port, macs, scan_timeout, config.addresses);
 
     exit(exitcode::OK)
 }
@@ -59,7 +77,7 @@ fn main() {
         .version("0.0.1")
         .author("Jan Starzak <jan.starzak@gmail.com>")
         .about("Connect to a Yongnuo LED light over Bluetooth LE and control it using OSC.")
-        .long_about("Connect to a Yongnuo LED light over Bluetooth LE and control it using OSC.\nSupported OSC addresses are: \\red, \\green, \\blue, \\warm, \\cool.\nAccepting single float values in range 0..1")
+        .long_about("Connect to a Yongnuo LED light over Bluetooth LE and control it using OSC.\nSupported OSC addresses are: \\red, \\green, \\blue, \\warm, \\cool, \\hsv, \\hue, \\saturation, \\brightness, \\kelvin, \\state.\nAccepting single float values in range 0..1 (\\hsv takes three: hue, saturation, value; \\kelvin takes a color temperature in 2700..6500)\nWhen controlling several lights (multiple -m), prefix the address with its 1-based instance number, e.g. \\2\\red, to target a single light; a bare address broadcasts to all of them.\nAfter every command (or a \\state query) the server replies to the sender with the committed \\red.. \\cool values and a \\connected flag.")
         .subcommand(
             SubCommand::with_name("discover")
                 .about("Discover available Bluetooth LE devices")
@@ -69,6 +87,13 @@ fn main() {
                         .long("timeout")
                         .takes_value(true)
                         .default_value(DEFAULT_TIMEOUT),
+                )
+                .arg(
+                    Arg::with_name("prefix")
+                        .short("n")
+                        .long("name-prefix")
+                        .takes_value(true)
+                        .help("Only show devices whose advertised name starts with this prefix"),
                 ),
         )
         .subcommand(
@@ -78,15 +103,22 @@ fn main() {
                     Arg::with_name("mac")
                         .short("m")
                         .takes_value(true)
-                        .required(true)
-                        .help("MAC address of the device"),
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("MAC address of the device. Pass -m more than once to control several lights at once. When omitted, the lights listed in the config file are used; if there are none, the bridge scans and auto-connects to the first Yongnuo light it finds"),
                 )
                 .arg(
                     Arg::with_name("port")
                         .short("p")
                         .takes_value(true)
-                        .help("UDP port where the OSC server should listen for messages")
-                        .default_value(DEFAULT_PORT),
+                        .help("UDP port where the OSC server should listen for messages (default: 8000)"),
+                )
+                .arg(
+                    Arg::with_name("config")
+                        .short("c")
+                        .long("config")
+                        .takes_value(true)
+                        .help("YAML file with port, lights and OSC address remapping; command-line flags take precedence"),
                 ),
         )
         .get_matches();
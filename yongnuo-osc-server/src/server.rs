@@ -1,20 +1,26 @@
 use num_traits::ToPrimitive;
 use rosc::decoder::decode as osc_decode;
+use rosc::encoder::encode as osc_encode;
 use rosc::{OscBundle, OscMessage, OscPacket, OscType};
+use std::collections::HashMap;
 use std::net::{SocketAddr, UdpSocket};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Mutex;
-use std::sync::{Arc, Condvar};
+use std::sync::{Arc, Barrier, Condvar};
 use std::thread;
 use std::time::Duration;
 
-use btleplug::api::{BDAddr, Central, Characteristic, Peripheral as ApiPeripheral, UUID};
+use btleplug::api::{
+    BDAddr, Central, CentralEvent, Characteristic, Peripheral as ApiPeripheral, UUID,
+};
 #[cfg(target_os = "linux")]
 use btleplug::bluez::{adapter::ConnectedAdapter, manager::Manager};
 #[cfg(target_os = "macos")]
 use btleplug::corebluetooth::{adapter::Adapter, manager::Manager};
 #[cfg(target_os = "windows")]
 use btleplug::winrtble::{adapter::Adapter, manager::Manager};
+use std::sync::mpsc::{channel, Receiver};
 
 #[cfg(any(target_os = "windows", target_os = "macos"))]
 fn get_central(manager: &Manager) -> Adapter {
@@ -56,227 +62,792 @@ struct WhiteState {
     cool: u8,
 }
 
+/// HSV coordinates behind the last `/hue`, `/saturation`, `/brightness` or `/hsv` message, kept
+/// around so `/hue`, `/saturation` and `/brightness` can each change one coordinate while
+/// preserving the other two (e.g. `/brightness` rescales value but keeps the last hue/saturation).
+/// The device itself only understands `RGBState`/`WhiteState`.
+#[derive(Default, Clone)]
+struct HsvState {
+    hue: f32,
+    saturation: f32,
+    value: f32,
+}
+
 #[derive(Default, Clone)]
 struct LightState {
     rgb: RGBState,
     white: WhiteState,
+    hsv: HsvState,
+}
+
+/// Converts HSV (saturation and value in `0..1`) to the 0..255 RGB bytes the device expects.
+/// Hue wraps like an angle, so it's reduced into `0..1` first instead of requiring the caller
+/// to keep it there (an OSC `/hue` message can carry any float).
+fn hsv_to_rgb(hue: f32, saturation: f32, value: f32) -> (u8, u8, u8) {
+    let h6 = hue.rem_euclid(1.0) * 6.0;
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - (h6.rem_euclid(2.0) - 1.0).abs());
+    let m = value - chroma;
+
+    let (r, g, b) = match h6.floor() as i32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+
+    (
+        ((r + m) * 255.0).round().to_u8().unwrap_or(0),
+        ((g + m) * 255.0).round().to_u8().unwrap_or(0),
+        ((b + m) * 255.0).round().to_u8().unwrap_or(0),
+    )
+}
+
+fn apply_hsv(state: &mut LightState) -> StateModification {
+    let (red, green, blue) = hsv_to_rgb(state.hsv.hue, state.hsv.saturation, state.hsv.value);
+    state.rgb = RGBState { red, green, blue };
+    StateModification::RGB
 }
 
 #[derive(Clone, PartialEq)]
 enum StateModification {
     RGB,
     White,
+    /// A `/state` request: answer with feedback instead of pushing anything to the light.
+    Query,
     None,
 }
 
-fn send_rgb_state(state: &LightState, light: &impl ApiPeripheral, cmd_char: &Characteristic) {
+fn send_rgb_state(
+    state: &LightState,
+    light: &(impl ApiPeripheral + Clone),
+    cmd_char: &Characteristic,
+) -> bool {
     let red = state.rgb.red;
     let green = state.rgb.green;
     let blue = state.rgb.blue;
     println!("Sending RGB state: {0}, {1}, {2}", red, green, blue);
-    let result = light.command(cmd_char, &[0xae, 0xa1, red, green, blue, 0x56]);
-    if result.is_err() {
-        println!("Could not send RGB state: {:#?}", result)
-    }
+    command_with_timeout(
+        light,
+        cmd_char,
+        vec![0xae, 0xa1, red, green, blue, 0x56],
+        COMMAND_TIMEOUT,
+    )
 }
 
-fn send_white_state(state: &LightState, light: &impl ApiPeripheral, cmd_char: &Characteristic) {
+fn send_white_state(
+    state: &LightState,
+    light: &(impl ApiPeripheral + Clone),
+    cmd_char: &Characteristic,
+) -> bool {
     let cool = state.white.cool;
     let warm = state.white.warm;
     println!("Sending White state: {0}, {1}", cool, warm);
-    let result = light.command(cmd_char, &[0xae, 0xaa, 1, cool, warm, 0x56]);
-    if result.is_err() {
-        println!("Could not send RGB state: {:#?}", result)
+    command_with_timeout(
+        light,
+        cmd_char,
+        vec![0xae, 0xaa, 1, cool, warm, 0x56],
+        COMMAND_TIMEOUT,
+    )
+}
+
+/// Splits an instance-addressed OSC address like `/2/red` into the 1-based instance number
+/// and the remaining address (`/red`). Addresses with no leading number (e.g. plain `/red`)
+/// return `None`, meaning "broadcast to every light".
+fn parse_instance_address(addr: &str) -> (Option<usize>, &str) {
+    let rest = match addr.strip_prefix('/') {
+        Some(rest) => rest,
+        None => return (None, addr),
+    };
+    let mut parts = rest.splitn(2, '/');
+    match (parts.next(), parts.next()) {
+        (Some(index_str), Some(_)) if !index_str.is_empty() => match index_str.parse::<usize>() {
+            Ok(index) if index >= 1 => (Some(index), &addr[index_str.len() + 1..]),
+            _ => (None, addr),
+        },
+        _ => (None, addr),
     }
 }
 
-fn handle_message(message: OscMessage, state: &mut LightState) -> StateModification {
-    // println!(
-    //     "{}: {}",
-    //     message.addr,
-    //     (&message.args)
-    //         .into_iter()
-    //         .map(|v| v.to_string())
-    //         .collect::<Vec<String>>()
-    //         .join(" ")
-    // );
-
-    let value = (message.args).into_iter().nth(0);
-
-    match message.addr.as_ref() {
-        "/red" => {
-            let basic_value = (value.unwrap().float().unwrap_or(0.0) * 255.0)
-                .to_u8()
-                .unwrap_or(0);
-            state.rgb.red = basic_value;
-            return StateModification::RGB;
+const KELVIN_MIN: f32 = 2700.0;
+const KELVIN_MAX: f32 = 6500.0;
+
+fn take_float(args: &mut Vec<OscType>) -> f32 {
+    args.remove(0).float().unwrap_or(0.0)
+}
+
+fn apply_address(addr: &str, mut args: Vec<OscType>, state: &mut LightState) -> StateModification {
+    match addr {
+        "/red" if !args.is_empty() => {
+            state.rgb.red = (take_float(&mut args) * 255.0).to_u8().unwrap_or(0);
+            StateModification::RGB
+        }
+        "/green" if !args.is_empty() => {
+            state.rgb.green = (take_float(&mut args) * 255.0).to_u8().unwrap_or(0);
+            StateModification::RGB
         }
-        "/green" => {
-            let basic_value = (value.unwrap().float().unwrap_or(0.0) * 255.0)
-                .to_u8()
-                .unwrap_or(0);
-            state.rgb.green = basic_value;
-            return StateModification::RGB;
+        "/blue" if !args.is_empty() => {
+            state.rgb.blue = (take_float(&mut args) * 255.0).to_u8().unwrap_or(0);
+            StateModification::RGB
         }
-        "/blue" => {
-            let basic_value = (value.unwrap().float().unwrap_or(0.0) * 255.0)
-                .to_u8()
-                .unwrap_or(0);
-            state.rgb.blue = basic_value;
-            return StateModification::RGB;
+        "/warm" if !args.is_empty() => {
+            state.white.warm = (take_float(&mut args) * 99.0).to_u8().unwrap_or(0);
+            StateModification::White
         }
-        "/warm" => {
-            let basic_value = (value.unwrap().float().unwrap_or(0.0) * 99.0)
-                .to_u8()
-                .unwrap_or(0);
-            state.white.warm = basic_value;
-            return StateModification::White;
+        "/cool" if !args.is_empty() => {
+            state.white.cool = (take_float(&mut args) * 99.0).to_u8().unwrap_or(0);
+            StateModification::White
         }
-        "/cool" => {
-            let basic_value = (value.unwrap().float().unwrap_or(0.0) * 99.0)
-                .to_u8()
-                .unwrap_or(0);
-            state.white.cool = basic_value;
-            return StateModification::White;
+        "/hsv" if args.len() >= 3 => {
+            state.hsv.hue = take_float(&mut args);
+            state.hsv.saturation = take_float(&mut args);
+            state.hsv.value = take_float(&mut args);
+            apply_hsv(state)
         }
+        "/hue" if !args.is_empty() => {
+            state.hsv.hue = take_float(&mut args);
+            apply_hsv(state)
+        }
+        "/saturation" if !args.is_empty() => {
+            state.hsv.saturation = take_float(&mut args);
+            apply_hsv(state)
+        }
+        "/brightness" if !args.is_empty() => {
+            state.hsv.value = take_float(&mut args);
+            apply_hsv(state)
+        }
+        "/kelvin" if !args.is_empty() => {
+            let kelvin = take_float(&mut args);
+            let t = ((kelvin - KELVIN_MIN) / (KELVIN_MAX - KELVIN_MIN)).clamp(0.0, 1.0);
+            state.white.warm = ((1.0 - t) * 99.0).round().to_u8().unwrap_or(0);
+            state.white.cool = (t * 99.0).round().to_u8().unwrap_or(0);
+            StateModification::White
+        }
+        "/state" => StateModification::Query,
         _ => {
-            println!("Unsupported OSC address: {0}", message.addr);
-            return StateModification::None;
+            println!("Unsupported OSC address: {0}", addr);
+            StateModification::None
+        }
+    }
+}
+
+/// Routes one OSC message to the light(s) it targets, returning the resulting modification
+/// for every light it touched. A bare address (`/red`) broadcasts to every light in `states`;
+/// an instance-addressed one (`/2/red`) only touches `states[1]`. `address_map` lets a config
+/// file remap a custom address (e.g. whatever a show-control app already emits) onto one of
+/// the built-in channel addresses before it's matched.
+fn handle_message(
+    message: OscMessage,
+    states: &mut [LightState],
+    address_map: &HashMap<String, String>,
+) -> Vec<(usize, StateModification)> {
+    let (instance, addr) = parse_instance_address(message.addr.as_ref());
+    let addr = address_map.get(addr).map(String::as_str).unwrap_or(addr);
+    let args = message.args;
+
+    match instance {
+        Some(index) if index >= 1 && index <= states.len() => {
+            let modification = apply_address(addr, args, &mut states[index - 1]);
+            vec![(index - 1, modification)]
+        }
+        Some(index) => {
+            println!("No light at instance index {0}", index);
+            vec![]
         }
+        None => states
+            .iter_mut()
+            .enumerate()
+            .map(|(i, state)| (i, apply_address(addr, args.clone(), state)))
+            .collect(),
     }
 }
 
-fn handle_bundle(bundle: OscBundle, state: &mut LightState) -> StateModification {
+fn handle_bundle(
+    bundle: OscBundle,
+    states: &mut [LightState],
+    address_map: &HashMap<String, String>,
+) -> Vec<(usize, StateModification)> {
     bundle
         .content
         .into_iter()
-        .map(|p| handle_packet(p, state))
-        .into_iter()
-        .last()
-        .unwrap_or(StateModification::None)
+        .flat_map(|p| handle_packet(p, states, address_map))
+        .collect()
 }
 
-fn handle_packet(packet: OscPacket, state: &mut LightState) -> StateModification {
+fn handle_packet(
+    packet: OscPacket,
+    states: &mut [LightState],
+    address_map: &HashMap<String, String>,
+) -> Vec<(usize, StateModification)> {
     match packet {
-        OscPacket::Message(osc_message) => handle_message(osc_message, state),
-        OscPacket::Bundle(osc_bundle) => handle_bundle(osc_bundle, state),
+        OscPacket::Message(osc_message) => handle_message(osc_message, states, address_map),
+        OscPacket::Bundle(osc_bundle) => handle_bundle(osc_bundle, states, address_map),
     }
 }
 
-pub fn serve(port: u16, mac: &str) {
-    let socket = UdpSocket::bind(SocketAddr::from(([0, 0, 0, 0], port)))
-        .ok()
-        .expect("Can't open server socket");
+/// Reports the committed channel values and link health back to `target`, so a touch surface
+/// can show what the light is actually doing rather than assuming its sliders took effect.
+/// `prefix` is the instance prefix (e.g. `/2`, or empty for a single-light setup).
+fn send_state_feedback(
+    socket: &UdpSocket,
+    target: SocketAddr,
+    prefix: &str,
+    state: &LightState,
+    connected: bool,
+) {
+    let channels: [(String, OscType); 6] = [
+        (
+            format!("{0}/red", prefix),
+            OscType::Float(state.rgb.red as f32 / 255.0),
+        ),
+        (
+            format!("{0}/green", prefix),
+            OscType::Float(state.rgb.green as f32 / 255.0),
+        ),
+        (
+            format!("{0}/blue", prefix),
+            OscType::Float(state.rgb.blue as f32 / 255.0),
+        ),
+        (
+            format!("{0}/warm", prefix),
+            OscType::Float(state.white.warm as f32 / 99.0),
+        ),
+        (
+            format!("{0}/cool", prefix),
+            OscType::Float(state.white.cool as f32 / 99.0),
+        ),
+        (
+            format!("{0}/connected", prefix),
+            OscType::Int(connected as i32),
+        ),
+    ];
+
+    for (addr, value) in channels {
+        let packet = OscPacket::Message(OscMessage {
+            addr,
+            args: vec![value],
+        });
+        match osc_encode(&packet) {
+            Ok(buf) => {
+                let _ = socket.send_to(&buf, target);
+            }
+            Err(err) => println!("Could not encode OSC feedback: {:#?}", err),
+        }
+    }
+}
 
-    let target_address = BDAddr::from_str(mac).ok().expect("Target address invalid");
-
-    print!("Connecting to device {0}... ", target_address);
-    let light_state_channel_send = Arc::new((
-        Mutex::new((LightState::default(), StateModification::None)),
-        Condvar::new(),
-    ));
-    let light_state_channel_recv = Arc::clone(&light_state_channel_send);
-
-    let threads = (
-        thread::spawn(move || {
-            let manager = Manager::new().unwrap();
-
-            // get the first bluetooth adapter
-            //
-            // connect to the adapter
-            let central = get_central(&manager);
-
-            // start scanning for devices
-            central
-                .start_scan()
-                .expect("Can't start scanning for the device");
-            // instead of waiting, you can use central.on_event to be notified of
-            // new devices
-            thread::sleep(Duration::from_secs(5));
-
-            // find the device we're interested in
-            let light = central
-                .peripherals()
-                .into_iter()
-                .find(|p| p.properties().address.eq(&target_address))
-                .expect("Could not find devices with the specified address");
-
-            // connect to the device
-            light.connect().ok().expect("Could not connect to device");
-
-            let send_char_uuid =
-                UUID::from_str("f0:00:aa:61:04:51:40:00:b0:00:00:00:00:00:00:00").unwrap();
-            // find the characteristic we want
-            let chars = light
-                .discover_characteristics()
-                .ok()
-                .expect("Could not discover characteristics");
-            let cmd_char = chars
-                .iter()
-                .find(|c| c.uuid == send_char_uuid)
-                .expect("Could not find matching command characteristic");
+const INIT_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+pub const DEFAULT_SCAN_TIMEOUT: u64 = 10;
+const COMMAND_CHAR_UUID: &str = "f0:00:aa:61:04:51:40:00:b0:00:00:00:00:00:00:00";
+// The Bluetooth spec caps a single GATT transaction at 30s; connect/discover use that as a
+// ceiling, but a color write should come back in well under a second, so it gets a much
+// tighter budget.
+const TRANSACTION_TIMEOUT: Duration = Duration::from_secs(30);
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(2);
+const HEALTH_LOG_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Runs `f` on a worker thread and waits up to `timeout` for it to finish, so a wedged BLE
+/// adapter can't block the caller (or the whole bridge) forever. A stuck worker thread is
+/// leaked rather than killed, since there's no safe way to cancel it.
+fn with_timeout<T, F>(timeout: Duration, f: F) -> Option<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let (result_send, result_recv) = channel();
+    thread::spawn(move || {
+        let _ = result_send.send(f());
+    });
+    result_recv.recv_timeout(timeout).ok()
+}
+
+/// Sends a command to the light with a bounded wait, treating a timeout the same as a
+/// transport error so both feed the same reconnect logic.
+fn command_with_timeout(
+    light: &(impl ApiPeripheral + Clone),
+    cmd_char: &Characteristic,
+    data: Vec<u8>,
+    timeout: Duration,
+) -> bool {
+    let light = light.clone();
+    let cmd_char = cmd_char.clone();
+    match with_timeout(timeout, move || light.command(&cmd_char, &data)) {
+        Some(Ok(())) => true,
+        Some(Err(err)) => {
+            println!("Could not send BLE command: {:#?}", err);
+            false
+        }
+        None => {
+            println!("BLE command timed out after {0}s", timeout.as_secs());
+            false
+        }
+    }
+}
+
+/// Scans until `target_address` is seen (or `scan_timeout` elapses) instead of sleeping for
+/// a fixed duration, so `find_and_connect` returns as soon as the light advertises. Takes the
+/// receiving end of an already-registered `on_event` subscription rather than creating its own,
+/// so repeated retries don't each leave a stale callback on the adapter.
+fn wait_for_device(
+    event_recv: &Receiver<BDAddr>,
+    target_address: BDAddr,
+    scan_timeout: Duration,
+) -> bool {
+    let start = std::time::Instant::now();
+    while start.elapsed() < scan_timeout {
+        match event_recv.recv_timeout(scan_timeout.saturating_sub(start.elapsed())) {
+            Ok(address) if address == target_address => return true,
+            Ok(_) => continue,
+            Err(_) => break,
+        }
+    }
+    false
+}
+
+/// Scan -> find -> connect -> rediscover characteristics -> re-send init. Returns `None` if
+/// any step fails so the caller can retry.
+fn find_and_connect(
+    central: &impl Central,
+    event_recv: &Receiver<BDAddr>,
+    target_address: BDAddr,
+    scan_timeout: Duration,
+) -> Option<(impl ApiPeripheral + Clone, Characteristic)> {
+    central.start_scan().ok()?;
+    if !wait_for_device(event_recv, target_address, scan_timeout) {
+        return None;
+    }
+
+    let light = central
+        .peripherals()
+        .into_iter()
+        .find(|p| p.properties().address.eq(&target_address))?;
+
+    let connect_light = light.clone();
+    with_timeout(TRANSACTION_TIMEOUT, move || connect_light.connect())?.ok()?;
+
+    let send_char_uuid = UUID::from_str(COMMAND_CHAR_UUID).unwrap();
+    let discover_light = light.clone();
+    let chars = with_timeout(TRANSACTION_TIMEOUT, move || {
+        discover_light.discover_characteristics()
+    })?
+    .ok()?;
+    let cmd_char = chars.into_iter().find(|c| c.uuid == send_char_uuid)?;
+
+    if !command_with_timeout(
+        &light,
+        &cmd_char,
+        vec![0xae, 0x33, 0x00, 0x00, 0x00, 0x56],
+        TRANSACTION_TIMEOUT,
+    ) {
+        return None;
+    }
+
+    Some((light, cmd_char))
+}
+
+/// Keeps retrying `find_and_connect` with exponential backoff until it succeeds. Used both
+/// for the initial connection and for reconnecting after the BLE link drops. Takes the receiver
+/// of a discovery subscription the caller registered once, so a light that reconnects many times
+/// over a long-running session doesn't leave one stale `on_event` callback behind per attempt.
+///
+/// Reconnection is keyed on `target_address` (the `BDAddr`) alone. `btleplug` at this version
+/// has no `bluest`-style stable device id to fall back on across address rotation, so that part
+/// of the original request can't be done in this tree.
+fn connect_with_backoff(
+    central: &impl Central,
+    event_recv: &Receiver<BDAddr>,
+    target_address: BDAddr,
+    scan_timeout: Duration,
+) -> (impl ApiPeripheral + Clone, Characteristic) {
+    let mut backoff = INIT_BACKOFF;
+    loop {
+        if let Some(result) = find_and_connect(central, event_recv, target_address, scan_timeout) {
+            return result;
+        }
+        println!(
+            "Could not connect to {0}, retrying in {1}s...",
+            target_address,
+            backoff.as_secs()
+        );
+        thread::sleep(backoff);
+        backoff = std::cmp::min(backoff * 2, MAX_BACKOFF);
+    }
+}
+
+/// Scans for `scan_timeout`, then connects to every peripheral seen and keeps the first one
+/// that exposes the Yongnuo command characteristic. Used by `connect` when no `-m` MAC is
+/// given, so the user doesn't have to copy the address out of `discover` first.
+fn find_verified_light(
+    central: &impl Central,
+    scan_timeout: Duration,
+) -> Option<(impl ApiPeripheral + Clone, Characteristic, BDAddr)> {
+    central.start_scan().ok()?;
+    thread::sleep(scan_timeout);
+
+    let send_char_uuid = UUID::from_str(COMMAND_CHAR_UUID).unwrap();
+
+    for peripheral in central.peripherals() {
+        let connect_peripheral = peripheral.clone();
+        match with_timeout(TRANSACTION_TIMEOUT, move || connect_peripheral.connect()) {
+            Some(Ok(())) => {}
+            _ => continue,
+        }
 
-            light
-                .command(cmd_char, &[0xae, 0x33, 0x00, 0x00, 0x00, 0x56])
-                .expect("Couldn't send initialize message");
+        let discover_peripheral = peripheral.clone();
+        let chars = match with_timeout(TRANSACTION_TIMEOUT, move || {
+            discover_peripheral.discover_characteristics()
+        }) {
+            Some(Ok(chars)) => chars,
+            _ => continue,
+        };
+
+        if let Some(cmd_char) = chars.into_iter().find(|c| c.uuid == send_char_uuid) {
+            let address = peripheral.properties().address;
+            if command_with_timeout(
+                &peripheral,
+                &cmd_char,
+                vec![0xae, 0x33, 0x00, 0x00, 0x00, 0x56],
+                TRANSACTION_TIMEOUT,
+            ) {
+                return Some((peripheral, cmd_char, address));
+            }
+        }
+    }
+
+    None
+}
+
+/// Keeps retrying `find_verified_light` with exponential backoff until a Yongnuo light is found.
+fn auto_connect_with_backoff(
+    central: &impl Central,
+    scan_timeout: Duration,
+) -> (impl ApiPeripheral + Clone, Characteristic, BDAddr) {
+    let mut backoff = INIT_BACKOFF;
+    loop {
+        if let Some(result) = find_verified_light(central, scan_timeout) {
+            return result;
+        }
+        println!(
+            "Could not find a Yongnuo light, retrying in {0}s...",
+            backoff.as_secs()
+        );
+        thread::sleep(backoff);
+        backoff = std::cmp::min(backoff * 2, MAX_BACKOFF);
+    }
+}
+
+type LightChannel = Arc<(
+    Mutex<(LightState, StateModification, Option<SocketAddr>)>,
+    Condvar,
+)>;
+
+/// Runs the reconnect-aware command loop for a single light, waiting on `barrier` after the
+/// initial connect so no OSC command is dispatched before every light in the rig is ready.
+/// After every successful command it reports the light's committed state and link health back
+/// to whoever sent the OSC message that produced it.
+#[allow(clippy::too_many_arguments)]
+fn run_light(
+    target_address: Option<BDAddr>,
+    channel: LightChannel,
+    barrier: Arc<Barrier>,
+    scan_timeout: Duration,
+    reply_socket: UdpSocket,
+    connected: Arc<AtomicBool>,
+    feedback_prefix: String,
+) {
+    let manager = Manager::new().unwrap();
+    let central = get_central(&manager);
+
+    // Registered once and reused for every connect_with_backoff call made over this light's
+    // entire lifetime, including every later reconnect, so repeated BLE drops don't each leave
+    // a stale on_event closure on the adapter.
+    let (event_send, event_recv) = channel();
+    central.on_event(Box::new(move |event| {
+        if let CentralEvent::DeviceDiscovered(address) | CentralEvent::DeviceUpdated(address) =
+            event
+        {
+            let _ = event_send.send(address);
+        }
+    }));
+
+    let (mut light, mut cmd_char, target_address) = match target_address {
+        Some(address) => {
+            print!("Connecting to device {0}... ", address);
+            let (light, cmd_char) =
+                connect_with_backoff(&central, &event_recv, address, scan_timeout);
+            (light, cmd_char, address)
+        }
+        None => {
+            print!("No MAC given, looking for a Yongnuo light... ");
+            auto_connect_with_backoff(&central, scan_timeout)
+        }
+    };
+    println!("Connected.");
+    connected.store(true, Ordering::Relaxed);
+
+    barrier.wait();
 
-            println!("Connected.");
+    let mut last_state = LightState::default();
+
+    loop {
+        let msg = {
+            let (lock, recv) = &*channel;
+            let mut msg = lock.lock().unwrap();
+            if msg.1 == StateModification::None {
+                msg = recv.wait(msg).unwrap();
+            }
+            let res = (*msg).clone();
+            msg.1 = StateModification::None;
+            res
+        };
+
+        let light_state = &msg.0;
+        let bundled_modification = &msg.1;
+        let sender = msg.2;
+        last_state = light_state.clone();
+
+        let sent_ok = match bundled_modification {
+            StateModification::RGB => send_rgb_state(light_state, &light, &cmd_char),
+            StateModification::White => send_white_state(light_state, &light, &cmd_char),
+            StateModification::Query | StateModification::None => true,
+        };
+
+        if sent_ok {
+            if let Some(target) = sender {
+                send_state_feedback(&reply_socket, target, &feedback_prefix, light_state, true);
+            }
+        } else {
+            connected.store(false, Ordering::Relaxed);
+            println!("Lost connection to {0}, reconnecting...", target_address);
 
             loop {
-                let msg = {
-                    let (lock, recv) = &*light_state_channel_recv;
-                    let mut msg = lock.lock().unwrap();
-                    if msg.1 == StateModification::None {
-                        msg = recv.wait(msg).unwrap();
-                    }
-                    let res = (*msg).clone();
-                    msg.1 = StateModification::None;
-                    res
-                };
+                let reconnected =
+                    connect_with_backoff(&central, &event_recv, target_address, scan_timeout);
+                light = reconnected.0;
+                cmd_char = reconnected.1;
+                connected.store(true, Ordering::Relaxed);
+                println!("Reconnected, restoring last known state.");
+
+                let rgb_ok = send_rgb_state(&last_state, &light, &cmd_char);
+                let white_ok = send_white_state(&last_state, &light, &cmd_char);
+                if rgb_ok && white_ok {
+                    break;
+                }
+                connected.store(false, Ordering::Relaxed);
+                println!("Lost connection again while restoring state, retrying...");
+            }
+
+            if let Some(target) = sender {
+                send_state_feedback(&reply_socket, target, &feedback_prefix, &last_state, true);
+            }
+        }
+    }
+}
 
-                let light_state = &msg.0;
-                let bundled_modification = &msg.1;
+pub fn serve(
+    port: u16,
+    macs: Vec<String>,
+    scan_timeout: u64,
+    address_map: HashMap<String, String>,
+) {
+    let socket = UdpSocket::bind(SocketAddr::from(([0, 0, 0, 0], port)))
+        .ok()
+        .expect("Can't open server socket");
 
-                match bundled_modification {
-                    StateModification::RGB => send_rgb_state(&light_state, &light, cmd_char),
-                    StateModification::White => send_white_state(&light_state, &light, cmd_char),
+    let scan_timeout = Duration::from_secs(scan_timeout);
+    let address_map = Arc::new(address_map);
+
+    let targets: Vec<Option<BDAddr>> = if macs.is_empty() {
+        vec![None]
+    } else {
+        macs.iter()
+            .map(|mac| Some(BDAddr::from_str(mac).ok().expect("Target address invalid")))
+            .collect()
+    };
+    let light_count = targets.len();
+    if light_count > 1 {
+        println!("Controlling {0} lights.", light_count);
+    }
+
+    let channels: Vec<LightChannel> = (0..light_count)
+        .map(|_| {
+            Arc::new((
+                Mutex::new((LightState::default(), StateModification::None, None)),
+                Condvar::new(),
+            ))
+        })
+        .collect();
+    let connected: Vec<Arc<AtomicBool>> = (0..light_count)
+        .map(|_| Arc::new(AtomicBool::new(false)))
+        .collect();
+    // +1 so the OSC thread also waits until every light has connected and initialized before
+    // the first command is dispatched.
+    let barrier = Arc::new(Barrier::new(light_count + 1));
+
+    let health_watchdog = {
+        let connected = connected.clone();
+        thread::spawn(move || loop {
+            thread::sleep(HEALTH_LOG_INTERVAL);
+            let statuses: Vec<String> = connected
+                .iter()
+                .enumerate()
+                .map(|(index, flag)| {
+                    let state = if flag.load(Ordering::Relaxed) {
+                        "connected"
+                    } else {
+                        "unreachable"
+                    };
+                    format!("light {0}: {1}", index + 1, state)
+                })
+                .collect();
+            println!("Link health: {0}", statuses.join(", "));
+        })
+    };
+
+    let light_threads: Vec<_> = targets
+        .into_iter()
+        .enumerate()
+        .map(|(index, target_address)| {
+            let channel = Arc::clone(&channels[index]);
+            let barrier = Arc::clone(&barrier);
+            let connected = Arc::clone(&connected[index]);
+            let reply_socket = socket.try_clone().expect("Could not clone OSC socket");
+            let feedback_prefix = if light_count > 1 {
+                format!("/{0}", index + 1)
+            } else {
+                String::new()
+            };
+            thread::spawn(move || {
+                run_light(
+                    target_address,
+                    channel,
+                    barrier,
+                    scan_timeout,
+                    reply_socket,
+                    connected,
+                    feedback_prefix,
+                )
+            })
+        })
+        .collect();
+
+    let osc_thread = thread::spawn(move || {
+        socket
+            .set_read_timeout(Some(Duration::new(0, 1)))
+            .expect("Could not set read timeout");
+
+        let mut lights_state = vec![LightState::default(); light_count];
+
+        barrier.wait();
+
+        loop {
+            let mut buf = [0; 4098];
+
+            let result = socket.recv_from(&mut buf);
+            let (_, src) = match result {
+                Ok(result) => result,
+                Err(_) => continue,
+            };
+
+            let osc_packet = osc_decode(&buf);
+            if let Err(err) = osc_packet {
+                // log
+                println!("Broken OSC message received: {:#?}", err);
+                continue;
+            }
+
+            let osc_packet = osc_packet.unwrap();
+            let modifications = handle_packet(osc_packet, &mut lights_state, &address_map);
+
+            for (index, modification) in modifications {
+                match modification {
                     StateModification::None => {}
+                    StateModification::Query => {
+                        let prefix = if light_count > 1 {
+                            format!("/{0}", index + 1)
+                        } else {
+                            String::new()
+                        };
+                        send_state_feedback(
+                            &socket,
+                            src,
+                            &prefix,
+                            &lights_state[index],
+                            connected[index].load(Ordering::Relaxed),
+                        );
+                    }
+                    _ => {
+                        let (lock, send) = &*channels[index];
+                        let mut light_state_send = lock.lock().unwrap();
+                        *light_state_send = (lights_state[index].clone(), modification, Some(src));
+                        send.notify_one();
+                    }
                 }
             }
-        }),
-        thread::spawn(move || {
-            socket
-                .set_read_timeout(Some(Duration::new(0, 1)))
-                .expect("Could not set read timeout");
+        }
+    });
 
-            let mut light_state = LightState::default();
+    for thread in light_threads {
+        thread.join().unwrap();
+    }
+    osc_thread.join().unwrap();
+    health_watchdog.join().unwrap();
+}
 
-            loop {
-                let mut buf = [0; 4098];
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-                let result = socket.recv_from(&mut buf);
-                if result.is_err() {
-                    continue;
-                }
+    #[test]
+    fn parse_instance_address_broadcasts_bare_addresses() {
+        assert_eq!(parse_instance_address("/red"), (None, "/red"));
+    }
 
-                let osc_packet = osc_decode(&buf);
-                if let Err(err) = osc_packet {
-                    // log
-                    println!("Broken OSC message received: {:#?}", err);
-                    continue;
-                }
+    #[test]
+    fn parse_instance_address_targets_a_single_instance() {
+        assert_eq!(parse_instance_address("/2/red"), (Some(2), "/red"));
+    }
 
-                let osc_packet = osc_packet.unwrap();
-                let this_modification = handle_packet(osc_packet, &mut light_state);
+    #[test]
+    fn parse_instance_address_keeps_the_original_numeral_width() {
+        assert_eq!(parse_instance_address("/007/red"), (Some(7), "/red"));
+    }
 
-                let (lock, send) = &*light_state_channel_send;
-                let mut light_state_send = lock.lock().unwrap();
-                *light_state_send = (light_state.clone(), this_modification);
-                send.notify_one();
-            }
-        }),
-    );
+    #[test]
+    fn parse_instance_address_treats_index_zero_as_a_broadcast() {
+        assert_eq!(parse_instance_address("/0/red"), (None, "/0/red"));
+    }
 
-    threads.0.join().unwrap();
-    threads.1.join().unwrap();
+    #[test]
+    fn parse_instance_address_rejects_a_non_numeric_instance() {
+        assert_eq!(parse_instance_address("/red/green"), (None, "/red/green"));
+    }
+
+    #[test]
+    fn parse_instance_address_rejects_an_index_that_overflows_usize() {
+        let addr = "/99999999999999999999/red";
+        assert_eq!(parse_instance_address(addr), (None, addr));
+    }
+
+    #[test]
+    fn hsv_to_rgb_covers_every_sextant() {
+        assert_eq!(hsv_to_rgb(0.0, 1.0, 1.0), (255, 0, 0));
+        assert_eq!(hsv_to_rgb(1.0 / 6.0, 1.0, 1.0), (255, 255, 0));
+        assert_eq!(hsv_to_rgb(2.0 / 6.0, 1.0, 1.0), (0, 255, 0));
+        assert_eq!(hsv_to_rgb(3.0 / 6.0, 1.0, 1.0), (0, 255, 255));
+        assert_eq!(hsv_to_rgb(4.0 / 6.0, 1.0, 1.0), (0, 0, 255));
+        assert_eq!(hsv_to_rgb(5.0 / 6.0, 1.0, 1.0), (255, 0, 255));
+    }
+
+    #[test]
+    fn hsv_to_rgb_wraps_hue_outside_0_1() {
+        assert_eq!(hsv_to_rgb(1.0, 1.0, 1.0), hsv_to_rgb(0.0, 1.0, 1.0));
+        assert_eq!(
+            hsv_to_rgb(-1.0 / 6.0, 1.0, 1.0),
+            hsv_to_rgb(5.0 / 6.0, 1.0, 1.0)
+        );
+    }
 }